@@ -1,3 +1,4 @@
+use base64::Engine;
 use clap::{Parser, Subcommand};
 use dialoguer::{FuzzySelect, Input};
 use glob::glob;
@@ -5,12 +6,28 @@ use indicatif::ProgressBar;
 use platform_dirs::AppDirs;
 use postgrest::Postgrest;
 use sailfish::TemplateOnce;
-use space::{eyre, template, Config, Format, Language, Node, Result, StorageClient};
-use std::{borrow::Cow, fs::File, io::Write, path::PathBuf, time::Duration};
+use space::{
+    eyre, now, sha256_hex, template, Config, ConfigFile, Format, Language, Node, Result,
+    StorageClient,
+};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    path::PathBuf,
+    time::Duration,
+};
 use uuid::Uuid;
+use wasi_common::pipe::{ReadPipe, WritePipe};
+use wasmtime::{Engine, Linker, Module, Store};
+use wasmtime_wasi::sync::WasiCtxBuilder;
 
 #[derive(Parser)]
 struct Args {
+    /// Profile from `space.toml` to use, e.g. `dev` or `prod`
+    #[arg(long, global = true)]
+    profile: Option<String>,
     /// Subcommand to run
     #[command(subcommand)]
     command: Command,
@@ -19,15 +36,41 @@ struct Args {
 #[derive(Subcommand)]
 enum Command {
     /// Login by store token locally
-    Login,
+    Login(Login),
     /// Create a new WASM project
     New(New),
     /// Upload WASM project to Space Operator
-    Upload,
+    Upload(Upload),
     /// Generate JSON from dialogue
     Generate,
     /// Manually upload WASM, source code and json to Space Operator
     Manual(Manual),
+    /// Run the built WASM node locally against a single input
+    Run(Run),
+    /// Run the built WASM node locally against a set of input fixtures
+    Test(Test),
+    /// List your published nodes
+    List,
+    /// Update mutable fields of a published node without re-uploading binaries
+    Update(Update),
+    /// Retire a published node version so it stops being offered
+    Yank(Yank),
+    /// Restore a previously yanked node version
+    Unyank(Unyank),
+}
+
+#[derive(Parser)]
+struct Login {
+    /// Paste a long-lived authorization token instead of opening a browser, for CI
+    #[arg(long)]
+    token: Option<String>,
+}
+
+#[derive(Parser)]
+struct Upload {
+    /// Skip gzip-compressing the WASM binary before upload
+    #[arg(long)]
+    no_compress: bool,
 }
 
 #[derive(Parser)]
@@ -38,6 +81,29 @@ struct Manual {
     json: PathBuf,
     /// Path to source code
     source_code: PathBuf,
+    /// Skip gzip-compressing the WASM binary before upload
+    #[arg(long)]
+    no_compress: bool,
+}
+
+#[derive(Parser)]
+struct Run {
+    /// Path to node declaration, defaults to `node.json` in the project root
+    #[arg(long)]
+    json: Option<PathBuf>,
+    /// Path to a JSON input file, reads from stdin when omitted
+    #[arg(long)]
+    input: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct Test {
+    /// Path to node declaration, defaults to `node.json` in the project root
+    #[arg(long)]
+    json: Option<PathBuf>,
+    /// Glob of JSON input fixtures to run
+    #[arg(long, default_value = "tests/*.json")]
+    fixtures: String,
 }
 
 #[derive(Parser)]
@@ -46,50 +112,227 @@ struct New {
     name: String,
 }
 
+#[derive(Parser)]
+struct Update {
+    /// Node to update, e.g. `my-node.0.1`
+    unique_node_id: String,
+    /// New description
+    #[arg(long)]
+    description: Option<String>,
+    /// New visibility
+    #[arg(long)]
+    is_public: Option<bool>,
+    /// New one-time payment
+    #[arg(long)]
+    price_one_time: Option<f64>,
+    /// New price per run
+    #[arg(long)]
+    price_per_run: Option<f64>,
+    /// New license type, e.g. `MIT`
+    #[arg(long)]
+    license_type: Option<String>,
+}
+
+#[derive(Parser)]
+struct Yank {
+    /// Node to yank, e.g. `my-node.0.1`
+    unique_node_id: String,
+}
+
+#[derive(Parser)]
+struct Unyank {
+    /// Node to unyank, e.g. `my-node.0.1`
+    unique_node_id: String,
+}
+
 fn config_path() -> Result<PathBuf> {
     let app_dirs = AppDirs::new(Some("space"), false).ok_or(eyre!("Config location is invalid"))?;
     std::fs::create_dir_all(&app_dirs.config_dir)?;
     Ok(app_dirs.config_dir.join("space.toml"))
 }
 
-fn read_config() -> Result<Config> {
-    let config_file = config_path()?;
-    let raw = std::fs::read_to_string(config_file)?;
-    Ok(toml::from_str(&raw)?)
+fn read_config_file() -> Result<ConfigFile> {
+    let path = config_path()?;
+    match std::fs::read_to_string(path) {
+        Ok(raw) => Ok(toml::from_str(&raw)?),
+        Err(_) => Ok(ConfigFile::default()),
+    }
+}
+
+fn write_config_file(config_file: &ConfigFile) -> Result<()> {
+    let mut file = File::create(config_path()?)?;
+    file.write_all(toml::to_string(config_file)?.as_bytes())?;
+    Ok(())
+}
+
+/// Resolve the effective `Config` for `profile`, layering env var overrides on top.
+fn read_config(profile: &str) -> Result<Config> {
+    Ok(read_config_file()?.resolve(profile))
+}
+
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: i64,
+}
+
+/// Authorization-code-with-PKCE flow: open the browser, catch the redirect on a
+/// transient localhost listener, then exchange the code for tokens.
+async fn oauth_login() -> Result<(String, Option<String>, Option<i64>)> {
+    use sha2::{Digest, Sha256};
+
+    // Generate a PKCE verifier/challenge pair
+    let verifier_bytes = [Uuid::new_v4().into_bytes(), Uuid::new_v4().into_bytes()].concat();
+    let verifier = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(verifier_bytes);
+    let challenge =
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+
+    // Listen on a random local port for the authorization redirect
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let redirect_uri = format!("http://127.0.0.1:{}/callback", listener.local_addr()?.port());
+
+    let auth_url = format!(
+        "https://spaceoperator.com/oauth/authorize?response_type=code&client_id=space-cli&redirect_uri={redirect_uri}&code_challenge={challenge}&code_challenge_method=S256"
+    );
+    open::that(&auth_url)?;
+    println!("Opened browser for login, waiting for redirect...");
+
+    // Accept the single redirect and pull the authorization code out of the request line
+    let (mut stream, _) = listener.accept()?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let code = request_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|path| path.split("code=").nth(1))
+        .and_then(|rest| rest.split('&').next())
+        .ok_or(eyre!("Authorization code not found in redirect"))?
+        .to_string();
+
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n\
+          <html><body>Logged in, you can close this tab.</body></html>",
+    )?;
+
+    // Exchange the code for an access+refresh token pair
+    let response = reqwest::Client::new()
+        .post("https://spaceoperator.com/oauth/token")
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("client_id", "space-cli"),
+            ("code", &code),
+            ("redirect_uri", &redirect_uri),
+            ("code_verifier", &verifier),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<TokenResponse>()
+        .await?;
+
+    Ok((
+        format!("Bearer {}", response.access_token),
+        response.refresh_token,
+        Some(now() + response.expires_in),
+    ))
+}
+
+/// Silently refresh `config.authorization` in-place and on disk when it is near expiry.
+async fn ensure_fresh_token(profile: &str, config: &mut Config) -> Result<()> {
+    if !config.needs_refresh() {
+        return Ok(());
+    }
+
+    let refresh_token = config
+        .refresh_token
+        .clone()
+        .ok_or(eyre!("Access token expired, run `space login` again"))?;
+
+    let response = reqwest::Client::new()
+        .post("https://spaceoperator.com/oauth/token")
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", "space-cli"),
+            ("refresh_token", &refresh_token),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<TokenResponse>()
+        .await?;
+
+    config.authorization = format!("Bearer {}", response.access_token);
+    config.refresh_token = response.refresh_token.or(Some(refresh_token));
+    config.expires_at = Some(now() + response.expires_in);
+
+    // Persist the refreshed token back into its profile, leaving others untouched
+    let mut config_file = read_config_file().unwrap_or_default();
+    let mut stored = config_file.profile(profile);
+    stored.authorization = config.authorization.clone();
+    stored.refresh_token = config.refresh_token.clone();
+    stored.expires_at = config.expires_at;
+    config_file.profiles.insert(profile.to_string(), stored);
+    write_config_file(&config_file)?;
+
+    Ok(())
+}
+
+/// The Supabase user id (the `sub` claim) embedded in `authorization`'s access token, so
+/// queries can filter to the caller's own nodes instead of relying solely on RLS.
+fn current_user_id(authorization: &str) -> Result<String> {
+    let token = authorization
+        .strip_prefix("Bearer ")
+        .ok_or(eyre!("Not a bearer token"))?;
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or(eyre!("Malformed access token"))?;
+    let claims = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(payload)?;
+    let claims: serde_json::Value = serde_json::from_slice(&claims)?;
+    claims["sub"]
+        .as_str()
+        .map(String::from)
+        .ok_or(eyre!("Access token missing `sub` claim"))
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
+    dotenvy::dotenv().ok();
     let args = Args::parse();
+    let profile = args
+        .profile
+        .clone()
+        .unwrap_or_else(|| ConfigFile::DEFAULT_PROFILE.to_string());
 
     // Parse arguments
     match args.command {
-        Command::Login => {
-            // Get defaults
-            let defaults = read_config().unwrap_or_default();
-
-            let authorization = Input::<String>::new()
-                .with_prompt("Authorization token")
-                .report(false)
-                .interact_text()?;
-
-            // Create config file
-            let config_file = config_path()?;
-            let message = format!("Wrote settings to {}", config_file.display());
+        Command::Login(Login { token }) => {
+            // Get defaults, preserving the other profiles on disk
+            let mut config_file = read_config_file()?;
+            let defaults = config_file.profile(&profile);
+
+            let (authorization, refresh_token, expires_at) = match token {
+                Some(token) => (token, None, None),
+                None => oauth_login().await?,
+            };
 
-            // Serialize to toml
-            let mut file = File::create(config_file)?;
             let config = Config {
                 apikey: defaults.apikey,
                 endpoint: defaults.endpoint,
                 authorization,
+                refresh_token,
+                expires_at,
             };
-            let toml = toml::to_string(&config)?;
+            config_file.profiles.insert(profile.clone(), config);
+            write_config_file(&config_file)?;
 
-            // Write to file
-            file.write_all(toml.as_bytes())?;
-            println!("{message}");
+            println!(
+                "Wrote settings to {} (profile `{profile}`)",
+                config_path()?.display()
+            );
         }
         Command::New(New { name }) => {
             // Ask for language
@@ -137,50 +380,309 @@ async fn main() -> Result<()> {
 
             println!("Created new project `{name}`");
         }
-        Command::Upload => {
+        Command::Upload(Upload { no_compress }) => {
             // Find root config file then change it
             let directory = find_root(std::env::current_dir()?)?;
             std::env::set_current_dir(directory)?;
             let language = find_language(std::env::current_dir()?)?;
 
-            // Upload based on language
-            match language {
-                Language::Zig => {
-                    // Build project in release mode
-                    duct::cmd!("zig", "build").run()?;
-
-                    // Find the files then upload
-                    let wasm = glob("zig-out/lib/*.wasm")?
-                        .next()
-                        .ok_or(eyre!("WASM not found"))??;
-                    let source_code = PathBuf::from("src/main.zig");
-                    upload(wasm, source_code, None).await?;
-                }
-                Language::Rust => {
-                    // Build project in release mode
-                    duct::cmd!("cargo", "build", "--release", "--target", "wasm32-wasi").run()?;
-
-                    // Find the files then upload
-                    let wasm = glob("target/wasm32-wasi/release/*.wasm")?
-                        .next()
-                        .ok_or(eyre!("WASM not found"))??;
-                    let source_code = PathBuf::from("src/lib.rs");
-                    upload(wasm, source_code, None).await?;
-                }
-            };
+            // Build then upload
+            let wasm = build_wasm(language)?;
+            let source_code = source_code_path(language);
+            upload(&profile, wasm, source_code, None, !no_compress).await?;
         }
-        Command::Manual(Manual { wasm, source_code, json }) => upload(wasm, source_code, Some(json)).await?,
+        Command::Manual(Manual {
+            wasm,
+            source_code,
+            json,
+            no_compress,
+        }) => upload(&profile, wasm, source_code, Some(json), !no_compress).await?,
         Command::Generate => {
+            // No network or disk access happens here, so there's no effective `Config`
+            // (profile/env-layered or otherwise) for this command to resolve.
             let format = read_format(None)?;
             let json = serde_json::to_string_pretty(&format)?;
             println!("{json}");
         }
+        Command::Run(Run { json, input }) => {
+            // Find root config file then change it
+            let directory = find_root(std::env::current_dir()?)?;
+            std::env::set_current_dir(directory)?;
+            let language = find_language(std::env::current_dir()?)?;
+
+            let wasm = build_wasm(language)?;
+            let format = read_node_format(json.as_ref())?;
+
+            let input = match input {
+                Some(path) => std::fs::read_to_string(path)?,
+                None => {
+                    let mut buffer = String::new();
+                    std::io::stdin().read_to_string(&mut buffer)?;
+                    buffer
+                }
+            };
+
+            let output = run_fixture(&wasm, &format, &input)?;
+            println!("{output}");
+        }
+        Command::Test(Test { json, fixtures }) => {
+            // Find root config file then change it
+            let directory = find_root(std::env::current_dir()?)?;
+            std::env::set_current_dir(directory)?;
+            let language = find_language(std::env::current_dir()?)?;
+
+            let wasm = build_wasm(language)?;
+            let format = read_node_format(json.as_ref())?;
+
+            let mut failed = 0;
+            for entry in glob(&fixtures)? {
+                let path = entry?;
+                let input = std::fs::read_to_string(&path)?;
+                match run_fixture(&wasm, &format, &input) {
+                    Ok(output) => println!("{}: ok\n{output}", path.display()),
+                    Err(error) => {
+                        failed += 1;
+                        println!("{}: FAILED\n{error}", path.display());
+                    }
+                }
+            }
+
+            if failed > 0 {
+                return Err(eyre!("{failed} fixture(s) failed"));
+            }
+        }
+        Command::List => {
+            let mut config = read_config(&profile)?;
+            ensure_fresh_token(&profile, &mut config).await?;
+            let user_id = current_user_id(&config.authorization)?;
+            let client = Postgrest::new(format!("{}/rest/v1", config.endpoint))
+                .insert_header("apikey", config.apikey)
+                .insert_header("authorization", config.authorization);
+
+            let nodes = client
+                .from("nodes")
+                .select("unique_node_id,data,isPublic,isYanked,priceOneTime,pricePerRun")
+                .eq("owner", &user_id)
+                .execute()
+                .await?
+                .error_for_status()?
+                .json::<Vec<NodeSummary>>()
+                .await?;
+
+            for node in nodes {
+                let visibility = match (node.is_public, node.is_yanked) {
+                    (_, true) => "yanked",
+                    (true, false) => "public",
+                    (false, false) => "private",
+                };
+                println!(
+                    "{} v{} {visibility} one-time:{} per-run:{}",
+                    node.unique_node_id, node.data.version, node.price_one_time, node.price_per_run
+                );
+            }
+        }
+        Command::Update(Update {
+            unique_node_id,
+            description,
+            is_public,
+            price_one_time,
+            price_per_run,
+            license_type,
+        }) => {
+            let mut config = read_config(&profile)?;
+            ensure_fresh_token(&profile, &mut config).await?;
+            let client = Postgrest::new(format!("{}/rest/v1", config.endpoint))
+                .insert_header("apikey", config.apikey)
+                .insert_header("authorization", config.authorization);
+
+            let mut nodes = client
+                .from("nodes")
+                .eq("unique_node_id", &unique_node_id)
+                .select("*")
+                .execute()
+                .await?
+                .error_for_status()?
+                .json::<Vec<Node>>()
+                .await?;
+            let mut node = nodes
+                .pop()
+                .ok_or(eyre!("Node `{unique_node_id}` not found"))?;
+
+            if let Some(description) = description {
+                node.data.description = description;
+            }
+            if let Some(is_public) = is_public {
+                node.is_public = is_public;
+            }
+            if let Some(price_one_time) = price_one_time {
+                node.price_one_time = price_one_time;
+            }
+            if let Some(price_per_run) = price_per_run {
+                node.price_per_run = price_per_run;
+            }
+            if let Some(license_type) = license_type {
+                node.license_type = license_type;
+            }
+
+            client
+                .from("nodes")
+                .eq("unique_node_id", &unique_node_id)
+                .update(serde_json::to_string(&node)?)
+                .execute()
+                .await?
+                .error_for_status()?;
+
+            println!("Updated {unique_node_id}");
+        }
+        Command::Yank(Yank { unique_node_id }) => set_yanked(&profile, &unique_node_id, true).await?,
+        Command::Unyank(Unyank { unique_node_id }) => {
+            set_yanked(&profile, &unique_node_id, false).await?
+        }
     }
 
     // Return success
     Ok(())
 }
 
+#[derive(serde::Deserialize)]
+struct NodeSummary {
+    unique_node_id: String,
+    data: space::Data,
+    #[serde(rename = "isPublic")]
+    is_public: bool,
+    #[serde(rename = "isYanked")]
+    is_yanked: bool,
+    #[serde(rename = "priceOneTime")]
+    price_one_time: f64,
+    #[serde(rename = "pricePerRun")]
+    price_per_run: f64,
+}
+
+/// Flip a node's `isYanked` flag, retiring or restoring it without touching its binaries.
+async fn set_yanked(profile: &str, unique_node_id: &str, is_yanked: bool) -> Result<()> {
+    let mut config = read_config(profile)?;
+    ensure_fresh_token(profile, &mut config).await?;
+    let client = Postgrest::new(format!("{}/rest/v1", config.endpoint))
+        .insert_header("apikey", config.apikey)
+        .insert_header("authorization", config.authorization);
+
+    client
+        .from("nodes")
+        .eq("unique_node_id", unique_node_id)
+        .update(serde_json::to_string(&serde_json::json!({ "isYanked": is_yanked }))?)
+        .execute()
+        .await?
+        .error_for_status()?;
+
+    println!(
+        "{} {unique_node_id}",
+        if is_yanked { "Yanked" } else { "Unyanked" }
+    );
+    Ok(())
+}
+
+/// Build the project in release mode for its target and return the produced WASM file.
+fn build_wasm(language: Language) -> Result<PathBuf> {
+    match language {
+        Language::Zig => {
+            duct::cmd!("zig", "build").run()?;
+            Ok(glob("zig-out/lib/*.wasm")?
+                .next()
+                .ok_or(eyre!("WASM not found"))??)
+        }
+        Language::Rust => {
+            duct::cmd!("cargo", "build", "--release", "--target", "wasm32-wasi").run()?;
+            Ok(glob("target/wasm32-wasi/release/*.wasm")?
+                .next()
+                .ok_or(eyre!("WASM not found"))??)
+        }
+    }
+}
+
+fn source_code_path(language: Language) -> PathBuf {
+    match language {
+        Language::Zig => PathBuf::from("src/main.zig"),
+        Language::Rust => PathBuf::from("src/lib.rs"),
+    }
+}
+
+/// Read the node declaration used to validate `Run`/`Test` inputs and outputs,
+/// defaulting to `node.json` in the project root.
+fn read_node_format(json: Option<&PathBuf>) -> Result<Format> {
+    let default_path = PathBuf::from("node.json");
+    let path = json.unwrap_or(&default_path);
+
+    if !path.exists() {
+        return Err(eyre!(
+            "{} doesn't exist, generate one with `space generate` or pass --json",
+            path.display()
+        ));
+    }
+
+    let raw = std::fs::read_to_string(path)?;
+    Ok(Format::parse(&raw)?)
+}
+
+/// Validate `input`, run the module against it, then validate its output.
+fn run_fixture(wasm: &PathBuf, format: &Format, input: &str) -> Result<String> {
+    let parsed_input: serde_json::Value = serde_json::from_str(input)?;
+    format.validate_input(&parsed_input)?;
+
+    let output = run_wasm(wasm, input)?;
+
+    let parsed_output: serde_json::Value = serde_json::from_str(&output)?;
+    format.validate_output(&parsed_output)?;
+
+    Ok(output)
+}
+
+/// Instantiate `wasm` under WASI preview1, feed `input` to its stdin and capture stdout.
+fn run_wasm(wasm: &PathBuf, input: &str) -> Result<String> {
+    let engine = Engine::default();
+    let module = Module::from_file(&engine, wasm)?;
+
+    let stdin = ReadPipe::from(input);
+    let stdout = WritePipe::new_in_memory();
+    let stderr = WritePipe::new_in_memory();
+
+    let wasi = WasiCtxBuilder::new()
+        .stdin(Box::new(stdin))
+        .stdout(Box::new(stdout.clone()))
+        .stderr(Box::new(stderr.clone()))
+        .build();
+
+    let mut linker = Linker::new(&engine);
+    wasmtime_wasi::sync::add_to_linker(&mut linker, |s| s)?;
+    let mut store = Store::new(&engine, wasi);
+
+    let instance = linker.instantiate(&mut store, &module)?;
+    let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+
+    // A WASI command module (what `#[space]`'s `main` compiles to) may end through
+    // `proc_exit`, so a clean run surfaces either as `Ok(())` or as `Err(I32Exit(0))` here.
+    if let Err(trap) = start.call(&mut store, ()) {
+        let exit_code = trap.downcast_ref::<wasmtime_wasi::I32Exit>().map(|exit| exit.0);
+        if exit_code != Some(0) {
+            drop(store);
+            let message = stderr
+                .try_into_inner()
+                .map_err(|_| eyre!("stderr still in use"))?
+                .into_inner();
+            return Err(eyre!(
+                "node exited with an error: {trap}\n{}",
+                String::from_utf8_lossy(&message)
+            ));
+        }
+    }
+
+    drop(store);
+    let output = stdout
+        .try_into_inner()
+        .map_err(|_| eyre!("stdout still in use"))?
+        .into_inner();
+    Ok(String::from_utf8(output)?)
+}
+
 fn find_root(mut current: PathBuf) -> Result<PathBuf> {
     let candidates = ["Cargo.toml", "build.zig"];
     let file_exists = std::fs::read_dir(&current)?.any(|path| match path {
@@ -312,9 +814,16 @@ fn read_format(wasm: Option<&PathBuf>) -> Result<Format> {
     ))
 }
 
-async fn upload(wasm: PathBuf, source_code: PathBuf, json: Option<PathBuf>) -> Result<()> {
+async fn upload(
+    profile: &str,
+    wasm: PathBuf,
+    source_code: PathBuf,
+    json: Option<PathBuf>,
+    compress: bool,
+) -> Result<()> {
     // Get config
-    let config = read_config()?;
+    let mut config = read_config(profile)?;
+    ensure_fresh_token(profile, &mut config).await?;
     let client = StorageClient::new(&config.endpoint, &config.authorization);
 
     // Verify that web assembly exists
@@ -389,38 +898,66 @@ async fn upload(wasm: PathBuf, source_code: PathBuf, json: Option<PathBuf>) -> R
     ));
     spinner.enable_steady_tick(Duration::from_millis(10));
 
-    // Web assembly
+    // Web assembly, content-addressed so re-uploading the same binary is a no-op
     let wasm_name = wasm
         .file_name()
         .and_then(|it| it.to_str())
         .ok_or(eyre!("Invalid WASM path"))?;
     let bytes = std::fs::read(&wasm)?;
-    let storage_path = format!("{base_path}/{wasm_name}");
-    client
-        .from("node-files")
-        .upload(&storage_path, bytes)
-        .await?;
+    let wasm_sha256 = sha256_hex(&bytes);
+    let storage_path = format!("sha256/{wasm_sha256}/{wasm_name}");
+    let bucket = client.from("node-files");
+    let wasm_encoding = if let Some(existing_encoding) = bucket.existing_encoding(&storage_path).await? {
+        println!("{storage_path}: unchanged, skipping");
+        existing_encoding
+    } else {
+        let (bytes, encoding) = match compress {
+            true => {
+                let gzipped = space::gzip(&bytes).await?;
+                match gzipped.len() < bytes.len() {
+                    true => (gzipped, Some("gzip")),
+                    false => (bytes, None),
+                }
+            }
+            false => (bytes, None),
+        };
+        // Checksum the bytes actually going over the wire, not the raw content digest,
+        // so the server's integrity check matches what it receives.
+        let transfer_sha256 = sha256_hex(&bytes);
+        client
+            .from("node-files")
+            .upload(&storage_path, bytes, &transfer_sha256, encoding)
+            .await?;
+        encoding.map(String::from)
+    };
 
-    // Source code
+    // Source code, content-addressed the same way
     let source_code_name = source_code
         .file_name()
         .and_then(|it| it.to_str())
         .ok_or(eyre!("Invalid source code path"))?;
     let bytes = std::fs::read(&source_code)?;
-    let source_code = format!("{base_path}/{source_code_name}");
-    client
-        .from("node-files")
-        .upload(&source_code, bytes)
-        .await?;
+    let source_code_sha256 = sha256_hex(&bytes);
+    let source_code = format!("sha256/{source_code_sha256}/{source_code_name}");
+    let bucket = client.from("node-files");
+    if bucket.existing_encoding(&source_code).await?.is_some() {
+        println!("{source_code}: unchanged, skipping");
+    } else {
+        client
+            .from("node-files")
+            .upload(&source_code, bytes, &source_code_sha256, None)
+            .await?;
+    }
 
     // JSON
     let path = format!(
         "{base_path}/{}.json",
         format.data.display_name.to_lowercase().replace(" ", "_")
     );
+    let json_sha256 = sha256_hex(json.as_bytes());
     client
         .from("node-files")
-        .upload(&path, json.into_bytes())
+        .upload(&path, json.into_bytes(), &json_sha256, None)
         .await?;
 
     // Insert into database
@@ -431,6 +968,9 @@ async fn upload(wasm: PathBuf, source_code: PathBuf, json: Option<PathBuf>) -> R
         format.data.display_name.clone(),
         storage_path,
         source_code,
+        wasm_sha256,
+        source_code_sha256,
+        wasm_encoding,
         format.clone(),
         is_public,
         price_one_time,